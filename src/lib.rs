@@ -1,11 +1,45 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use strum_macros::Display;
+use thiserror::Error;
 use worker::*;
 
 const LTA_API_URL: &str = "http://datamall2.mytransport.sg/ltaodataservice/BusArrivalv2";
 const TELEGRAM_API_URL: &str = "https://api.telegram.org/bot";
 
+#[derive(Error, Debug)]
+enum BotError {
+    #[error("LTA DataMall request failed: {0}")]
+    Lta(reqwest::Error),
+    #[error("Telegram HTTP request failed: {0}")]
+    TelegramHttp(reqwest::Error),
+    #[error("Telegram API error {error_code}: {description}")]
+    TelegramApi {
+        error_code: i32,
+        description: String,
+        retry_after: Option<u32>,
+    },
+    #[error("Chat {0} is not allowed")]
+    Unauthorized(i64),
+    #[error("Failed to parse incoming update: {0}")]
+    ParseUpdate(String),
+}
+
+// Claude: Mirrors the `{ ok, error_code, description, parameters }` envelope Telegram wraps every response in
+#[derive(Deserialize, Debug)]
+struct TelegramResponseEnvelope {
+    ok: bool,
+    error_code: Option<i32>,
+    description: Option<String>,
+    parameters: Option<TelegramResponseParameters>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TelegramResponseParameters {
+    retry_after: Option<u32>,
+}
+
 #[derive(Deserialize, Debug)]
 struct Chat {
     id: i64,
@@ -13,6 +47,7 @@ struct Chat {
 
 #[derive(Deserialize, Debug)]
 struct Message {
+    message_id: i64,
     chat: Chat,
     text: Option<String>,
 }
@@ -50,17 +85,126 @@ enum TelegramMessageParseMode {
 #[strum(serialize_all = "camelCase")]
 enum TelegramMessageMethod {
     SendMessage,
+    EditMessageText,
     // Add other methods as needed
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 struct TelegramMessage {
     chat_id: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_id: Option<i64>,
     text: String,
     parse_mode: TelegramMessageParseMode,
     reply_markup: Option<ReplyMarkup>,
 }
 
+// Claude: The original design had a third variant, `AwaitingBusStopCode`, for a free-text
+// "send me a stop code" flow. The inline stop picker (`get_stop_picker_message`) replaced that
+// flow before it shipped, so there's no request that ever waits on free-text input - adding the
+// variant back would just be unreachable dead code.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+enum DialogueState {
+    Idle,
+    ShowingTimings { stop_code: String },
+}
+
+// Claude: Small trait so the KV-backed store can later be swapped (e.g. for tests or Durable Objects)
+trait StateStore {
+    async fn get(&self, chat_id: i64) -> Option<DialogueState>;
+    async fn set(&self, chat_id: i64, state: &DialogueState);
+}
+
+struct KvStateStore {
+    kv: kv::KvStore,
+}
+
+impl KvStateStore {
+    fn new(kv: kv::KvStore) -> Self {
+        Self { kv }
+    }
+}
+
+impl StateStore for KvStateStore {
+    async fn get(&self, chat_id: i64) -> Option<DialogueState> {
+        self.kv
+            .get(&chat_id.to_string())
+            .json::<DialogueState>()
+            .await
+            .ok()
+            .flatten()
+    }
+
+    async fn set(&self, chat_id: i64, state: &DialogueState) {
+        let put = match self.kv.put(&chat_id.to_string(), state) {
+            Ok(put) => put,
+            Err(e) => {
+                console_error!("Failed to serialize dialogue state for chat {}: {}", chat_id, e);
+                return;
+            }
+        };
+        if let Err(e) = put.execute().await {
+            console_error!("Failed to persist dialogue state for chat {}: {}", chat_id, e);
+        }
+    }
+}
+
+// Claude: A user's saved bus stops, so the picker keyboard can be built per-chat instead of from one env-wide code
+trait StopListStore {
+    async fn list(&self, chat_id: i64) -> Vec<String>;
+    async fn add(&self, chat_id: i64, stop_code: &str);
+    async fn remove(&self, chat_id: i64, stop_code: &str);
+}
+
+struct KvStopListStore {
+    kv: kv::KvStore,
+}
+
+impl KvStopListStore {
+    fn new(kv: kv::KvStore) -> Self {
+        Self { kv }
+    }
+
+    async fn save(&self, chat_id: i64, stops: &[String]) {
+        let put = match self.kv.put(&chat_id.to_string(), stops) {
+            Ok(put) => put,
+            Err(e) => {
+                console_error!("Failed to serialize saved stops for chat {}: {}", chat_id, e);
+                return;
+            }
+        };
+        if let Err(e) = put.execute().await {
+            console_error!("Failed to persist saved stops for chat {}: {}", chat_id, e);
+        }
+    }
+}
+
+impl StopListStore for KvStopListStore {
+    async fn list(&self, chat_id: i64) -> Vec<String> {
+        self.kv
+            .get(&chat_id.to_string())
+            .json::<Vec<String>>()
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+    }
+
+    async fn add(&self, chat_id: i64, stop_code: &str) {
+        let mut stops = self.list(chat_id).await;
+        if !stops.iter().any(|s| s == stop_code) {
+            stops.push(stop_code.to_string());
+            self.save(chat_id, &stops).await;
+        }
+    }
+
+    async fn remove(&self, chat_id: i64, stop_code: &str) {
+        let mut stops = self.list(chat_id).await;
+        stops.retain(|s| s != stop_code);
+        self.save(chat_id, &stops).await;
+    }
+}
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "PascalCase")]
 struct BusArrivalResponse {
@@ -80,17 +224,83 @@ struct BusService {
 #[serde(rename_all = "PascalCase")]
 struct BusArrival {
     estimated_arrival: Option<String>,
+    // Claude: Crowding level - SEA (seats available), SDA (standing available), LSD (limited standing)
+    load: Option<String>,
+    // Claude: Deck configuration - SD (single), DD (double), BD (bendy)
+    #[serde(rename = "Type")]
+    bus_type: Option<String>,
+    // Claude: "WAB" when the bus is wheelchair accessible, empty otherwise
+    feature: Option<String>,
 }
 struct BusTiming {
     service_no: String,
     next_arrival: String,
     next_arrival_2: String,
     next_arrival_3: String,
+    next_arrival_minutes: Option<i64>,
+}
+
+// Claude: A user's request to be pushed a message when `service_no` is about to arrive at `stop_code`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Subscription {
+    chat_id: i64,
+    stop_code: String,
+    service_no: String,
+    windows: Vec<ArrivalWindow>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ArrivalWindow {
+    min_minutes: i64,
+    max_minutes: i64,
 }
 
-fn format_bus_timings_message(bus_timings: Vec<BusTiming>) -> String {
+// Claude: All subscriptions live under one KV key, matching run_subscription_sweep's single sweep-everything read
+trait SubscriptionStore {
+    async fn all(&self) -> Vec<Subscription>;
+    async fn add(&self, subscription: Subscription);
+}
+
+struct KvSubscriptionStore {
+    kv: kv::KvStore,
+}
+
+impl KvSubscriptionStore {
+    fn new(kv: kv::KvStore) -> Self {
+        Self { kv }
+    }
+}
+
+impl SubscriptionStore for KvSubscriptionStore {
+    async fn all(&self) -> Vec<Subscription> {
+        self.kv
+            .get("all")
+            .json::<Vec<Subscription>>()
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+    }
+
+    async fn add(&self, subscription: Subscription) {
+        let mut subscriptions = self.all().await;
+        subscriptions.push(subscription);
+        let put = match self.kv.put("all", &subscriptions) {
+            Ok(put) => put,
+            Err(e) => {
+                console_error!("Failed to serialize subscriptions: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = put.execute().await {
+            console_error!("Failed to persist subscriptions: {}", e);
+        }
+    }
+}
+
+fn format_bus_timings_message(bus_timings: &[BusTiming]) -> String {
     let timings: Vec<String> = bus_timings
-        .into_iter()
+        .iter()
         .map(|bus| {
             format!(
                 "*Service No:* {}\n\
@@ -103,28 +313,104 @@ fn format_bus_timings_message(bus_timings: Vec<BusTiming>) -> String {
         })
         .collect();
 
-    format!("*Bus Timings:*\n\n{}", timings.join("\n"))
+    // Claude: Lets the user see that an in-place refresh actually happened
+    let updated_at = Utc::now().format("%H:%M:%S");
+    format!(
+        "*Bus Timings:*\n\n{}\n_updated {}_",
+        timings.join("\n"),
+        updated_at
+    )
 }
 
-fn get_telegram_message_with_request_button(chat_id: i64, text: &str) -> TelegramMessage {
-    // Claude: Create request button for bus timings
-    let request_button = TelegramButton {
-        text: "Request Bus Timings".to_string(),
-        callback_data: "request_timings".to_string(),
-    };
+// Claude: Renders one button per saved stop; callback_data encodes `timings:<stop_code>` so
+// handle_request's callback branch can route straight to the stop the user tapped
+fn get_stop_picker_message(
+    chat_id: i64,
+    text: &str,
+    stop_codes: &[String],
+    message_id: Option<i64>,
+) -> TelegramMessage {
+    let inline_keyboard = stop_codes
+        .iter()
+        .map(|stop_code| {
+            vec![TelegramButton {
+                text: stop_code.clone(),
+                callback_data: format!("timings:{}", stop_code),
+            }]
+        })
+        .collect();
 
-    // Claude: Prepare Telegram message with optional reply markup (buttons)
     TelegramMessage {
         chat_id,
+        message_id,
         text: text.to_string(),
         parse_mode: TelegramMessageParseMode::MarkdownV2,
-        reply_markup: Some(ReplyMarkup {
-            inline_keyboard: vec![vec![request_button]],
-        }),
+        reply_markup: Some(ReplyMarkup { inline_keyboard }),
     }
 }
 
-async fn fetch_bus_timings(lta_api_key: &str, bus_stop_code: &str) -> Result<Vec<BusTiming>> {
+// Claude: Adds one button per service (`timings:<stop_code>:<service_no>`) above the saved-stop
+// buttons, so a rider can drill a multi-service stop down to the single bus they're waiting for
+fn get_timings_message(
+    chat_id: i64,
+    text: &str,
+    stop_code: &str,
+    bus_timings: &[BusTiming],
+    stop_codes: &[String],
+    message_id: Option<i64>,
+) -> TelegramMessage {
+    let mut inline_keyboard: Vec<Vec<TelegramButton>> = bus_timings
+        .iter()
+        .map(|bus| {
+            vec![TelegramButton {
+                text: format!("Filter to Service {}", bus.service_no),
+                callback_data: format!("timings:{}:{}", stop_code, bus.service_no),
+            }]
+        })
+        .collect();
+
+    inline_keyboard.extend(stop_codes.iter().map(|stop_code| {
+        vec![TelegramButton {
+            text: stop_code.clone(),
+            callback_data: format!("timings:{}", stop_code),
+        }]
+    }));
+
+    TelegramMessage {
+        chat_id,
+        message_id,
+        text: text.to_string(),
+        parse_mode: TelegramMessageParseMode::MarkdownV2,
+        reply_markup: Some(ReplyMarkup { inline_keyboard }),
+    }
+}
+
+// Claude: Compact glyphs for crowding, deck type, and wheelchair accessibility
+fn arrival_glyphs(arrival: &BusArrival) -> String {
+    let load_glyph = match arrival.load.as_deref() {
+        Some("SEA") => "🟢",
+        Some("SDA") => "🟡",
+        Some("LSD") => "🔴",
+        _ => "",
+    };
+    let type_glyph = match arrival.bus_type.as_deref() {
+        Some("SD") => "🚍",
+        Some("DD") => "🚌",
+        Some("BD") => "🚋",
+        _ => "",
+    };
+    let feature_glyph = match arrival.feature.as_deref() {
+        Some("WAB") => "♿",
+        _ => "",
+    };
+    format!("{}{}{}", load_glyph, type_glyph, feature_glyph)
+}
+
+async fn fetch_bus_timings(
+    lta_api_key: &str,
+    bus_stop_code: &str,
+    service_filter: Option<&str>,
+) -> std::result::Result<Vec<BusTiming>, BotError> {
     // Claude: Prepare headers for LTA API request
     let client = reqwest::Client::new();
     let resp = client
@@ -133,9 +419,9 @@ async fn fetch_bus_timings(lta_api_key: &str, bus_stop_code: &str) -> Result<Vec
         .header("accept", "application/json")
         .send()
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(BotError::Lta)?;
 
-    let bus_arrival_resp: BusArrivalResponse = resp.json().await.map_err(|e| e.to_string())?;
+    let bus_arrival_resp: BusArrivalResponse = resp.json().await.map_err(BotError::Lta)?;
 
     console_debug!("LTA API response: {:#?}", bus_arrival_resp);
 
@@ -144,28 +430,43 @@ async fn fetch_bus_timings(lta_api_key: &str, bus_stop_code: &str) -> Result<Vec
     let bus_timings = bus_arrival_resp
         .services
         .into_iter()
+        .filter(|service| service_filter.is_none_or(|filter| service.service_no == filter))
         .map(|service| {
-            let format_arrival = |arrival: &BusArrival| {
+            // Claude: Returns both the display label (with crowding/deck/accessibility glyphs) and,
+            // when known, the raw minutes until arrival
+            let format_arrival = |arrival: &BusArrival| -> (String, Option<i64>) {
                 if let Some(time) = &arrival.estimated_arrival {
                     let arrival_time = DateTime::parse_from_rfc3339(time)
                         .map(|dt| dt.timestamp())
                         .unwrap_or(0);
                     let diff_minutes = (arrival_time - now) / 60;
-                    if diff_minutes <= 0 {
+                    let label = if diff_minutes <= 0 {
                         "ARR".to_string()
                     } else {
                         format!("{} min", diff_minutes)
-                    }
+                    };
+                    let glyphs = arrival_glyphs(arrival);
+                    let label = if glyphs.is_empty() {
+                        label
+                    } else {
+                        format!("{} {}", label, glyphs)
+                    };
+                    (label, Some(diff_minutes))
                 } else {
-                    "NIL".to_string()
+                    ("NIL".to_string(), None)
                 }
             };
 
+            let (next_arrival, next_arrival_minutes) = format_arrival(&service.next_bus);
+            let (next_arrival_2, _) = format_arrival(&service.next_bus2);
+            let (next_arrival_3, _) = format_arrival(&service.next_bus3);
+
             BusTiming {
                 service_no: service.service_no,
-                next_arrival: format_arrival(&service.next_bus),
-                next_arrival_2: format_arrival(&service.next_bus2),
-                next_arrival_3: format_arrival(&service.next_bus3),
+                next_arrival,
+                next_arrival_2,
+                next_arrival_3,
+                next_arrival_minutes,
             }
         })
         .collect();
@@ -173,32 +474,84 @@ async fn fetch_bus_timings(lta_api_key: &str, bus_stop_code: &str) -> Result<Vec
     Ok(bus_timings)
 }
 
-async fn send_message(
-    telegram_api_key: &str,
+async fn post_telegram_message(
+    client: &reqwest::Client,
+    telegram_url: &str,
     telegram_message: &TelegramMessage,
-) -> Result<reqwest::Response> {
-    // Claude: Prepare Telegram API request
-    let client = reqwest::Client::new();
-    let method = TelegramMessageMethod::SendMessage.to_string();
-    let telegram_url = format!("{}{}/{}", TELEGRAM_API_URL, telegram_api_key, method);
-    console_debug!("Telegram API URL: {}", telegram_url);
-    client
+) -> std::result::Result<TelegramResponseEnvelope, BotError> {
+    let resp = client
         .post(telegram_url)
         .header("Content-Type", "application/json")
         .json(telegram_message)
         .send()
         .await
-        .map_err(|e| Error::from(e.to_string()))
+        .map_err(BotError::TelegramHttp)?;
+
+    let envelope: TelegramResponseEnvelope = resp.json().await.map_err(BotError::TelegramHttp)?;
+    console_debug!("Telegram API response: {:#?}", envelope);
+    Ok(envelope)
+}
+
+fn telegram_api_error(envelope: TelegramResponseEnvelope) -> BotError {
+    BotError::TelegramApi {
+        error_code: envelope.error_code.unwrap_or(0),
+        description: envelope.description.unwrap_or_default(),
+        retry_after: envelope.parameters.and_then(|p| p.retry_after),
+    }
+}
+
+async fn send_message(
+    telegram_api_key: &str,
+    telegram_message: &TelegramMessage,
+) -> std::result::Result<(), BotError> {
+    // Claude: Edit the existing message in place when one is being refreshed, otherwise post a new one
+    let method = if telegram_message.message_id.is_some() {
+        TelegramMessageMethod::EditMessageText
+    } else {
+        TelegramMessageMethod::SendMessage
+    }
+    .to_string();
+    let client = reqwest::Client::new();
+    let telegram_url = format!("{}{}/{}", TELEGRAM_API_URL, telegram_api_key, method);
+    console_debug!("Telegram API URL: {}", telegram_url);
+
+    let envelope = post_telegram_message(&client, &telegram_url, telegram_message).await?;
+    if envelope.ok {
+        return Ok(());
+    }
+
+    let retry_after = envelope.parameters.as_ref().and_then(|p| p.retry_after);
+    let Some(seconds) = retry_after else {
+        return Err(telegram_api_error(envelope));
+    };
+
+    // Claude: Telegram asked us to back off (HTTP 429 flood control) - wait, then retry once
+    console_error!(
+        "Telegram flood control hit, retrying in {} second(s)",
+        seconds
+    );
+    Delay::from(Duration::from_secs(seconds as u64)).await;
+
+    let retry_envelope = post_telegram_message(&client, &telegram_url, telegram_message).await?;
+    if retry_envelope.ok {
+        return Ok(());
+    }
+    Err(telegram_api_error(retry_envelope))
 }
 
 async fn handle_request(
     mut req: Request,
     lta_api_key: &str,
     telegram_api_key: &str,
-    bus_stop_code: &str,
     allowed_chat_id: &str,
-) -> Result<Response> {
-    let update: TelegramUpdate = req.json().await?;
+    state_store: &impl StateStore,
+    stop_list_store: &impl StopListStore,
+    subscription_store: &impl SubscriptionStore,
+) -> std::result::Result<Response, BotError> {
+    let update: TelegramUpdate = req
+        .json()
+        .await
+        .map_err(|e| BotError::ParseUpdate(e.to_string()))?;
 
     console_log!("Incoming Request: {:#?}", update);
 
@@ -207,57 +560,356 @@ async fn handle_request(
     } else if let Some(message) = &update.message {
         Ok(message.chat.id)
     } else {
-        Err("No chat id found in request")
+        Err(BotError::ParseUpdate("No chat id found in request".to_string()))
     }?;
 
     // Check if chat ID is allowed
-    let () = if chat_id.to_string() == allowed_chat_id {
-        Ok(())
-    } else {
-        Err(format!("Chat ID {} is not allowed", chat_id))
-    }?;
-
-    let telegram_message = match update.callback_query {
-        Some(callback_query) => match callback_query.data.as_str() {
-            "request_timings" => {
-                // Claude: Fetch and send bus timings when button is pressed
-                let bus_timings = fetch_bus_timings(lta_api_key, bus_stop_code).await?;
-                let message = format_bus_timings_message(bus_timings);
-
-                console_log!("Sending message: {}", message);
+    if chat_id.to_string() != allowed_chat_id {
+        return Err(BotError::Unauthorized(chat_id));
+    }
 
-                Ok(get_telegram_message_with_request_button(chat_id, &message))
+    let current_state = state_store.get(chat_id).await.unwrap_or(DialogueState::Idle);
+    console_debug!("Dialogue state for chat {}: {:?}", chat_id, current_state);
+
+    let (telegram_message, next_state) = match update.callback_query {
+        Some(callback_query) => {
+            let parts: Vec<&str> = callback_query.data.splitn(3, ':').collect();
+            match parts.as_slice() {
+                ["timings", stop_code] => {
+                    let bus_timings = fetch_bus_timings(lta_api_key, stop_code, None).await?;
+                    let message = format_bus_timings_message(&bus_timings);
+                    let stops = stop_list_store.list(chat_id).await;
+                    let message_id = callback_query.message.message_id;
+                    (
+                        Ok(get_timings_message(
+                            chat_id,
+                            &message,
+                            stop_code,
+                            &bus_timings,
+                            &stops,
+                            Some(message_id),
+                        )),
+                        DialogueState::ShowingTimings {
+                            stop_code: stop_code.to_string(),
+                        },
+                    )
+                }
+                ["timings", stop_code, service_no] => {
+                    let bus_timings =
+                        fetch_bus_timings(lta_api_key, stop_code, Some(service_no)).await?;
+                    let message = format_bus_timings_message(&bus_timings);
+                    let stops = stop_list_store.list(chat_id).await;
+                    let message_id = callback_query.message.message_id;
+                    (
+                        Ok(get_stop_picker_message(
+                            chat_id,
+                            &message,
+                            &stops,
+                            Some(message_id),
+                        )),
+                        DialogueState::ShowingTimings {
+                            stop_code: stop_code.to_string(),
+                        },
+                    )
+                }
+                _ => (
+                    Err(BotError::ParseUpdate(format!(
+                        "Invalid callback query, expected \"timings:<stop>\" or \"timings:<stop>:<service_no>\" but got \"{}\"",
+                        callback_query.data
+                    ))),
+                    current_state,
+                ),
             }
-            data => Err(format!(
-                "Invalid callback query, expected \"request_timings\" but got \"{}\"",
-                data
-            )),
-        },
+        }
         None => match update.message {
-            None => Err("No message found in request".to_string()),
+            None => (
+                Err(BotError::ParseUpdate("No message found in request".to_string())),
+                current_state,
+            ),
             Some(message) => match message.text.as_deref() {
-                None => Err("No message found in request".to_string()),
+                None => (
+                    Err(BotError::ParseUpdate("No message found in request".to_string())),
+                    current_state,
+                ),
                 Some("/start") => {
-                    let welcome_message = "Welcome to the Bus Arrival Bot! Click the button below to request bus timings:";
-                    Ok(get_telegram_message_with_request_button(
-                        chat_id,
-                        welcome_message,
-                    ))
+                    let stops = stop_list_store.list(chat_id).await;
+                    let welcome_message = if stops.is_empty() {
+                        "Welcome to the Bus Arrival Bot\\! Use /add CODE to save a bus stop, e\\.g\\. /add 83139\\."
+                    } else {
+                        "Welcome back\\! Pick a saved stop below, or /add CODE to save another\\."
+                    };
+                    (
+                        Ok(get_stop_picker_message(chat_id, welcome_message, &stops, None)),
+                        DialogueState::Idle,
+                    )
+                }
+                Some("/refresh") => match &current_state {
+                    DialogueState::ShowingTimings { stop_code } => {
+                        let bus_timings = fetch_bus_timings(lta_api_key, stop_code, None).await?;
+                        let message = format_bus_timings_message(&bus_timings);
+                        let stops = stop_list_store.list(chat_id).await;
+                        (
+                            Ok(get_timings_message(
+                                chat_id,
+                                &message,
+                                stop_code,
+                                &bus_timings,
+                                &stops,
+                                None,
+                            )),
+                            current_state.clone(),
+                        )
+                    }
+                    DialogueState::Idle => (
+                        Ok(get_stop_picker_message(
+                            chat_id,
+                            "No timings to refresh yet\\. Pick a saved stop below\\.",
+                            &stop_list_store.list(chat_id).await,
+                            None,
+                        )),
+                        DialogueState::Idle,
+                    ),
+                },
+                Some("/list") => {
+                    let stops = stop_list_store.list(chat_id).await;
+                    let list_message = if stops.is_empty() {
+                        "You haven't saved any bus stops yet\\. Use /add CODE to save one\\."
+                    } else {
+                        "Your saved bus stops:"
+                    };
+                    (
+                        Ok(get_stop_picker_message(chat_id, list_message, &stops, None)),
+                        DialogueState::Idle,
+                    )
+                }
+                Some(text) if text.starts_with("/add ") => {
+                    let stop_code = text["/add ".len()..].trim();
+                    if !is_valid_stop_code(stop_code) {
+                        (
+                            Err(BotError::ParseUpdate(format!(
+                                "Invalid stop code, expected a non-empty numeric code but got \"{}\"",
+                                stop_code
+                            ))),
+                            current_state,
+                        )
+                    } else {
+                        stop_list_store.add(chat_id, stop_code).await;
+                        let stops = stop_list_store.list(chat_id).await;
+                        let confirmation = format!("Saved stop {}\\.", stop_code);
+                        (
+                            Ok(get_stop_picker_message(chat_id, &confirmation, &stops, None)),
+                            DialogueState::Idle,
+                        )
+                    }
+                }
+                Some(text) if text.starts_with("/remove ") => {
+                    let stop_code = text["/remove ".len()..].trim();
+                    if !is_valid_stop_code(stop_code) {
+                        (
+                            Err(BotError::ParseUpdate(format!(
+                                "Invalid stop code, expected a non-empty numeric code but got \"{}\"",
+                                stop_code
+                            ))),
+                            current_state,
+                        )
+                    } else {
+                        stop_list_store.remove(chat_id, stop_code).await;
+                        let stops = stop_list_store.list(chat_id).await;
+                        let confirmation = format!("Removed stop {}\\.", stop_code);
+                        (
+                            Ok(get_stop_picker_message(chat_id, &confirmation, &stops, None)),
+                            DialogueState::Idle,
+                        )
+                    }
+                }
+                Some(text) if text.starts_with("/watch ") => {
+                    let args = text["/watch ".len()..].trim();
+                    match parse_watch_args(args) {
+                        Some((stop_code, service_no, window)) => {
+                            subscription_store
+                                .add(Subscription {
+                                    chat_id,
+                                    stop_code: stop_code.to_string(),
+                                    service_no: service_no.to_string(),
+                                    windows: vec![window],
+                                })
+                                .await;
+                            let confirmation = format!(
+                                "Watching service {} at stop {} for {}\\-{} min\\.",
+                                service_no, stop_code, window.min_minutes, window.max_minutes
+                            );
+                            let stops = stop_list_store.list(chat_id).await;
+                            (
+                                Ok(get_stop_picker_message(chat_id, &confirmation, &stops, None)),
+                                DialogueState::Idle,
+                            )
+                        }
+                        None => (
+                            Err(BotError::ParseUpdate(format!(
+                                "Invalid /watch arguments, expected \"<stop_code> <service_no> <min>-<max>\" but got \"{}\"",
+                                args
+                            ))),
+                            current_state,
+                        ),
+                    }
                 }
-                Some(text) => Err(format!(
-                    "Invalid message, expected \"/start\" but got \"{}\"",
-                    text
-                )),
+                Some(text) => (
+                    Err(BotError::ParseUpdate(format!(
+                        "Invalid message, expected \"/start\", \"/list\", \"/add <code>\", \"/remove <code>\", \"/refresh\" or \"/watch <code> <service> <min>-<max>\" but got \"{}\"",
+                        text
+                    ))),
+                    current_state,
+                ),
             },
         },
-    }?;
+    };
+    let telegram_message = telegram_message?;
+
+    state_store.set(chat_id, &next_state).await;
 
     console_log!("Outgoing Response: {:#?}", telegram_message);
-    let resp = send_message(telegram_api_key, &telegram_message).await?;
-    let resp_json = resp.text().await.map_err(|e| e.to_string())?;
-    console_debug!("Telegram API response: {:#?}", resp_json);
+    send_message(telegram_api_key, &telegram_message).await?;
+
+    Response::ok("OK").map_err(|e| BotError::ParseUpdate(e.to_string()))
+}
+
+// Claude: LTA bus stop codes are fixed-width numeric strings; rejects the blank/garbage input
+// that slips through if a command's argument is missing or whitespace-only
+fn is_valid_stop_code(stop_code: &str) -> bool {
+    !stop_code.is_empty() && stop_code.chars().all(|c| c.is_ascii_digit())
+}
 
-    Response::ok("OK")
+// Claude: Parses "/watch <stop_code> <service_no> <min>-<max>" into its three pieces
+fn parse_watch_args(args: &str) -> Option<(&str, &str, ArrivalWindow)> {
+    let mut parts = args.split_whitespace();
+    let stop_code = parts.next()?;
+    let service_no = parts.next()?;
+    let range = parts.next()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    let (min_str, max_str) = range.split_once('-')?;
+    let min_minutes: i64 = min_str.parse().ok()?;
+    let max_minutes: i64 = max_str.parse().ok()?;
+    if min_minutes > max_minutes {
+        return None;
+    }
+    Some((
+        stop_code,
+        service_no,
+        ArrivalWindow {
+            min_minutes,
+            max_minutes,
+        },
+    ))
+}
+
+async fn check_subscription(
+    lta_api_key: &str,
+    telegram_api_key: &str,
+    dedup_kv: &kv::KvStore,
+    subscription: &Subscription,
+) -> std::result::Result<(), BotError> {
+    let bus_timings = fetch_bus_timings(
+        lta_api_key,
+        &subscription.stop_code,
+        Some(&subscription.service_no),
+    )
+    .await?;
+    let Some(timing) = bus_timings.first() else {
+        return Ok(());
+    };
+    let Some(minutes) = timing.next_arrival_minutes else {
+        return Ok(());
+    };
+
+    let in_window = subscription
+        .windows
+        .iter()
+        .any(|w| minutes >= w.min_minutes && minutes <= w.max_minutes);
+
+    // Claude: One dedup slot per (chat, stop, service), keyed to the arrival passing through the window -
+    // not to the per-tick minute count, so a bus ticking 5->4->3 through a 3-5 min window notifies once
+    let dedup_key = format!(
+        "{}:{}:{}",
+        subscription.chat_id, subscription.stop_code, subscription.service_no
+    );
+
+    if !in_window {
+        if let Err(e) = dedup_kv.delete(&dedup_key).await {
+            console_error!("Failed to clear dedup state for {}: {}", dedup_key, e);
+        }
+        return Ok(());
+    }
+
+    let already_notified = dedup_kv.get(&dedup_key).text().await.ok().flatten().is_some();
+    if already_notified {
+        return Ok(());
+    }
+
+    let message = format!(
+        "Service {} at stop {} is arriving in {} min\\!",
+        subscription.service_no, subscription.stop_code, minutes
+    );
+    let telegram_message = TelegramMessage {
+        chat_id: subscription.chat_id,
+        message_id: None,
+        text: message,
+        parse_mode: TelegramMessageParseMode::MarkdownV2,
+        reply_markup: None,
+    };
+    send_message(telegram_api_key, &telegram_message).await?;
+
+    if let Ok(put) = dedup_kv.put(&dedup_key, "notified") {
+        if let Err(e) = put.execute().await {
+            console_error!("Failed to persist dedup state for {}: {}", dedup_key, e);
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_subscription_sweep(env: Env) -> std::result::Result<(), BotError> {
+    let lta_api_key = env
+        .secret("LTA_API_KEY")
+        .map_err(|e| BotError::ParseUpdate(e.to_string()))?
+        .to_string();
+    let telegram_api_key = env
+        .secret("TELEGRAM_API_KEY")
+        .map_err(|e| BotError::ParseUpdate(e.to_string()))?
+        .to_string();
+    let subscription_store = KvSubscriptionStore::new(
+        env.kv("subscriptions")
+            .map_err(|e| BotError::ParseUpdate(e.to_string()))?,
+    );
+    let dedup_kv = env
+        .kv("subscription_dedup")
+        .map_err(|e| BotError::ParseUpdate(e.to_string()))?;
+
+    let subscriptions = subscription_store.all().await;
+
+    for subscription in &subscriptions {
+        if let Err(e) = check_subscription(&lta_api_key, &telegram_api_key, &dedup_kv, subscription).await {
+            console_error!(
+                "Failed to check subscription (chat {}, stop {}, service {}): {}",
+                subscription.chat_id,
+                subscription.stop_code,
+                subscription.service_no,
+                e
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[event(scheduled)]
+pub async fn scheduled(_event: ScheduledEvent, env: Env, _ctx: ScheduleContext) {
+    console_error_panic_hook::set_once();
+
+    if let Err(e) = run_subscription_sweep(env).await {
+        console_error!("Scheduled subscription sweep failed: {}", e);
+    }
 }
 
 #[event(fetch)]
@@ -267,28 +919,64 @@ pub async fn main(req: Request, env: Env, _ctx: worker::Context) -> Result<Respo
     let lta_api_key = env.secret("LTA_API_KEY")?.to_string();
     let telegram_api_key = env.secret("TELEGRAM_API_KEY")?.to_string();
     let kv = env.kv("bus_stops")?;
-    let bus_stop_code = kv
-        .get("code")
-        .text()
-        .await?
-        .ok_or("No bus stop codes found")?;
     // TODO: Using another KV namespace for this
     let allowed_chat_id = kv
         .get("chat_id")
         .text()
         .await?
         .ok_or("No allowed chat id found")?;
+    let state_store = KvStateStore::new(env.kv("dialogue_state")?);
+    let stop_list_store = KvStopListStore::new(env.kv("saved_stops")?);
+    let subscription_store = KvSubscriptionStore::new(env.kv("subscriptions")?);
 
     handle_request(
         req,
         &lta_api_key,
         &telegram_api_key,
-        &bus_stop_code,
         &allowed_chat_id,
+        &state_store,
+        &stop_list_store,
+        &subscription_store,
     )
     .await
-    .map_err(|e| {
-        console_error!("Error handling request: {}", e);
-        e
+    .or_else(|e| {
+        let status = match &e {
+            BotError::Lta(err) => {
+                console_error!("LTA DataMall error: {}", err);
+                // Claude: the upstream DataMall API is unreachable/misbehaving - distinct from Telegram
+                504
+            }
+            BotError::TelegramHttp(err) => {
+                console_error!("Telegram HTTP error: {}", err);
+                502
+            }
+            BotError::TelegramApi {
+                error_code,
+                description,
+                retry_after,
+            } => {
+                console_error!(
+                    "Telegram API error {} ({}), retry_after={:?}",
+                    error_code,
+                    description,
+                    retry_after
+                );
+                // Claude: surface flood control distinctly from other Telegram API failures
+                if retry_after.is_some() {
+                    429
+                } else {
+                    502
+                }
+            }
+            BotError::Unauthorized(chat_id) => {
+                console_error!("Unauthorized chat id: {}", chat_id);
+                403
+            }
+            BotError::ParseUpdate(msg) => {
+                console_error!("Failed to parse update: {}", msg);
+                400
+            }
+        };
+        Response::error(e.to_string(), status)
     })
 }